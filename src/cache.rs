@@ -0,0 +1,112 @@
+use std::borrow::Borrow;
+use std::collections::{HashMap, VecDeque};
+
+use fontdue::Metrics;
+
+use crate::image::{Colors, Image};
+use crate::layout::GlyphPosition;
+use crate::source::GlyphSource;
+
+/// A rasterization key. The `f32` size is stored as its raw bits so it can
+/// participate in `Hash`/`Eq` — two sizes are the same glyph only when they are
+/// bit-for-bit identical.
+type GlyphKey = (char, usize, u32);
+
+/// Memoizes [`GlyphSource::rasterize`] so laying out many runs of the same font and size
+/// (captions, repeated glyphs) doesn't re-rasterize a character that has
+/// already been seen.
+///
+/// Backed by a `HashMap` and a `VecDeque` recording access order; once the map
+/// reaches `capacity` the least-recently-used entry at the front is evicted.
+pub struct GlyphCache {
+    capacity: usize,
+    map: HashMap<GlyphKey, (Metrics, Vec<u8>)>,
+    order: VecDeque<GlyphKey>,
+}
+
+impl GlyphCache {
+    /// A cache holding at most `capacity` rasterized glyphs. A `capacity` of 0
+    /// never evicts, so the cache grows without bound.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            map: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// The grey bitmap and metrics for a glyph, rasterizing and caching it on
+    /// the first request and serving it from the cache thereafter.
+    pub fn get<S: GlyphSource, F: Borrow<S>>(
+        &mut self,
+        fonts: &[F],
+        c: char,
+        font_index: usize,
+        size: f32,
+    ) -> &(Metrics, Vec<u8>) {
+        let key = (c, font_index, size.to_bits());
+
+        if self.map.contains_key(&key) {
+            self.touch(&key);
+        } else {
+            let font: &S = fonts[font_index].borrow();
+            let rasterized = font.rasterize(c, size);
+            self.insert(key, rasterized);
+        }
+
+        self.map.get(&key).unwrap()
+    }
+
+    /// Render laid-out glyphs into an image, pulling each bitmap from the cache
+    /// instead of rasterizing it afresh. `color` is the colour the glyph
+    /// coverage is drawn in, as in [`Image::draw_img`].
+    pub fn render<U: Clone, S: GlyphSource, F: Borrow<S>>(
+        &mut self,
+        glyphs: &[GlyphPosition<U>],
+        fonts: &[F],
+        color: (u8, u8, u8),
+    ) -> Image {
+        let mut width = 0;
+        let mut height = 0;
+        for glyph in glyphs {
+            width = width.max(glyph.x as usize + glyph.width);
+            height = height.max(glyph.y as usize + glyph.height);
+        }
+
+        let mut img = Image::new(width, height);
+        for glyph in glyphs {
+            let (metrics, bitmap) = self.get::<S, F>(fonts, glyph.c, glyph.font_index, glyph.font_size);
+
+            // A rasterized bitmap always matches its own metrics, so the length
+            // check cannot fail here.
+            let glyph_img =
+                Image::from_buffer(metrics.width, metrics.height, bitmap.clone(), Colors::Grey)
+                    .expect("rasterized glyph bitmap matches its metrics");
+
+            img.draw_img(glyph_img, glyph.x as isize, glyph.y as isize, true, color);
+        }
+
+        img
+    }
+
+    /// Move an already-present key to the most-recently-used end of the order.
+    fn touch(&mut self, key: &GlyphKey) {
+        if let Some(pos) = self.order.iter().position(|k| k == key) {
+            self.order.remove(pos);
+        }
+        self.order.push_back(*key);
+    }
+
+    /// Insert a freshly rasterized glyph, evicting the least-recently-used entry
+    /// first if the cache is full.
+    fn insert(&mut self, key: GlyphKey, value: (Metrics, Vec<u8>)) {
+        if self.capacity > 0 && self.map.len() >= self.capacity {
+            if let Some(evicted) = self.order.pop_front() {
+                self.map.remove(&evicted);
+            }
+        }
+
+        self.map.insert(key, value);
+        self.order.push_back(key);
+    }
+}
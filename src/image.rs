@@ -1,12 +1,28 @@
-#[derive(Debug, PartialEq)]
+use std::io::Write;
+
+use png::{BitDepth, ColorType, Encoder, EncodingError};
+use thiserror::Error;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Colors {
     RGB,
+    RGBA,
     Grey,
 }
 
+#[derive(Debug, Error)]
+pub enum ImageError {
+    #[error("buffer length was {got} but {expected} was expected")]
+    BufferLengthMismatch { expected: usize, got: usize },
+}
+
 pub struct Image {
     width: usize,
     height: usize,
+    // The storage format of `data`. Only `RGB` (3 bytes/pixel) and `RGBA`
+    // (4 bytes/pixel) are ever stored; `Grey` is an input format that
+    // `from_buffer` expands to `RGB`.
+    colors: Colors,
     data: Vec<u8>,
 }
 
@@ -21,40 +37,83 @@ impl Image {
         Self {
             width,
             height,
+            colors: Colors::RGB,
             data,
         }
     }
 
-    pub fn from_buffer(width: usize, height: usize, mut data: Vec<u8>, colors: Colors) -> Self {
+    /// An image backed by four bytes per pixel, fully transparent. Drawing onto
+    /// it composites over the transparent background with source-over alpha.
+    pub fn transparent(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            colors: Colors::RGBA,
+            data: vec![0; width * height * 4],
+        }
+    }
+
+    pub fn from_buffer(
+        width: usize,
+        height: usize,
+        mut data: Vec<u8>,
+        colors: Colors,
+    ) -> Result<Self, ImageError> {
         let expected_len = match colors {
             Colors::Grey => width * height,
             Colors::RGB => width * height * 3,
+            Colors::RGBA => width * height * 4,
         };
 
         if data.len() != expected_len {
-            panic!(
-                "Expected length to be {} but it's {}",
-                expected_len,
-                data.len()
-            );
+            return Err(ImageError::BufferLengthMismatch {
+                expected: expected_len,
+                got: data.len(),
+            });
         }
 
-        if colors == Colors::Grey {
+        // Grey expands to RGB; RGB and RGBA are stored as-is.
+        let colors = if colors == Colors::Grey {
             // Not the fastest, but it'll do.
             let mut colordata = Vec::with_capacity(width * height * 3);
             for byte in data.into_iter() {
                 colordata.extend_from_slice(&[byte, byte, byte]);
             }
             data = colordata;
-        }
+            Colors::RGB
+        } else {
+            colors
+        };
 
-        Self {
+        Ok(Self {
             width,
             height,
+            colors,
             data,
+        })
+    }
+
+    /// The PNG colour type matching this image's storage, for handing to the
+    /// encoder.
+    pub fn color_type(&self) -> ColorType {
+        match self.colors {
+            Colors::RGBA => ColorType::RGBA,
+            _ => ColorType::RGB,
         }
     }
 
+    /// Encode this image as a PNG to the given writer. A transparent (`RGBA`)
+    /// image is emitted as `ColorType::RGBA` so its straight-alpha channel
+    /// survives to disk; an opaque one as `ColorType::RGB`.
+    pub fn write_png<W: Write>(&self, writer: W) -> Result<(), EncodingError> {
+        let mut encoder = Encoder::new(writer, self.width as u32, self.height as u32);
+        encoder.set_color(self.color_type());
+        encoder.set_depth(BitDepth::Eight);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.data)
+    }
+
     pub fn width(&self) -> usize {
         self.width
     }
@@ -67,8 +126,16 @@ impl Image {
         &self.data
     }
 
+    /// The number of bytes per pixel in `data`.
+    pub fn channels(&self) -> usize {
+        match self.colors {
+            Colors::RGBA => 4,
+            _ => 3,
+        }
+    }
+
     pub fn xy_to_index(&self, x: usize, y: usize) -> usize {
-        (y as usize * self.width + x) * 3
+        (y as usize * self.width + x) * self.channels()
     }
 
     pub fn draw_img(
@@ -113,36 +180,68 @@ impl Image {
                         continue;
                     }
 
+                    // The glyph's grey coverage is the alpha with which
+                    // `replace_white_color` is painted over the existing pixel.
+                    let coverage = img_data[img_index];
+                    let a = coverage as f32 / 255.0;
+
                     let nrml = |c: u8| c as f32 / 255.0;
-                    let lerp = |c1: u8, c2: u8, a: u8| {
-                        ((nrml(c1) + nrml(a) * (nrml(c2) - nrml(c1))) * 255.0) as u8
-                    };
-
-                    self.data[our_index] = lerp(
-                        self.data[our_index],
-                        replace_white_color.0,
-                        img_data[img_index],
-                    );
-
-                    self.data[our_index + 1] = lerp(
-                        self.data[our_index + 1],
-                        replace_white_color.1,
-                        img_data[img_index],
-                    );
-
-                    self.data[our_index + 2] = lerp(
-                        self.data[our_index + 2],
-                        replace_white_color.2,
-                        img_data[img_index],
-                    );
+
+                    if self.colors == Colors::RGBA {
+                        // Source-over with straight (non-premultiplied) alpha:
+                        //     out_a = src_a + dst_a*(1 - src_a)
+                        //     out_c = (src_c*src_a + dst_c*dst_a*(1 - src_a)) / out_a
+                        // Dividing the composited colour back out by `out_a` keeps
+                        // it straight-alpha, so antialiased edges over a
+                        // transparent background don't darken toward black.
+                        let dst_a = nrml(self.data[our_index + 3]);
+                        let out_a = a + dst_a * (1.0 - a);
+
+                        let straight = |dst: u8, src: u8| {
+                            if out_a <= 0.0 {
+                                0.0
+                            } else {
+                                (nrml(src) * a + nrml(dst) * dst_a * (1.0 - a)) / out_a * 255.0
+                            }
+                        };
+
+                        self.data[our_index] =
+                            straight(self.data[our_index], replace_white_color.0) as u8;
+                        self.data[our_index + 1] =
+                            straight(self.data[our_index + 1], replace_white_color.1) as u8;
+                        self.data[our_index + 2] =
+                            straight(self.data[our_index + 2], replace_white_color.2) as u8;
+                        self.data[our_index + 3] = (out_a * 255.0) as u8;
+                    } else {
+                        // Opaque target: straight source-over of one channel:
+                        //     out = src*a + dst*(1 - a)
+                        let over =
+                            |dst: u8, src: u8| ((nrml(src) * a + nrml(dst) * (1.0 - a)) * 255.0);
+
+                        self.data[our_index] =
+                            over(self.data[our_index], replace_white_color.0) as u8;
+                        self.data[our_index + 1] =
+                            over(self.data[our_index + 1], replace_white_color.1) as u8;
+                        self.data[our_index + 2] =
+                            over(self.data[our_index + 2], replace_white_color.2) as u8;
+                    }
                 }
             }
         }
     }
 
     pub fn horizontal_line(&mut self, x: usize, y: usize, len: usize, color: (u8, u8, u8)) {
+        // A start outside the image has nothing on-screen to draw; clamp to a
+        // no-op rather than indexing past the buffer, matching the way `len` is
+        // clamped below.
+        if x >= self.width || y >= self.height {
+            return;
+        }
+
+        // Clamp the run so it stops at the right edge instead of spilling onto
+        // the next row (or past the buffer).
+        let len = len.min(self.width - x);
         for i in 0..len {
-            // TODO: Check x and y are valid coordiantes
             let index = self.xy_to_index(x + i, y);
 
             self.data[index] = color.0;
@@ -152,8 +251,16 @@ impl Image {
     }
 
     pub fn vertical_line(&mut self, x: usize, y: usize, len: usize, color: (u8, u8, u8)) {
+        // A start outside the image has nothing on-screen to draw; clamp to a
+        // no-op rather than indexing past the buffer, matching the way `len` is
+        // clamped below.
+        if x >= self.width || y >= self.height {
+            return;
+        }
+
+        // Clamp the run so it stops at the bottom edge.
+        let len = len.min(self.height - y);
         for i in 0..len {
-            // TODO: Check x and y are valid coordiantes
             let index = self.xy_to_index(x, y + i);
 
             self.data[index] = color.0;
@@ -1,6 +1,6 @@
 use std::borrow::Borrow;
 
-use fontdue::Font;
+use crate::source::GlyphSource;
 
 #[derive(Debug)]
 pub struct GlyphPosition<U: Clone> {
@@ -18,6 +18,24 @@ pub struct GlyphPosition<U: Clone> {
 pub struct LayoutSettings {
     pub horizontal_align: HorizontalAlign,
     pub line_height: LineHeight,
+    /// The column width, in pixels, that text is wrapped to. `None` lays every
+    /// run out on a single infinite line, breaking only on `'\n'` and
+    /// `'\u{2028}'`.
+    pub max_width: Option<f32>,
+    /// How soft line breaks are chosen when `max_width` is exceeded.
+    pub wrap_style: WrapStyle,
+    /// How the laid-out text is positioned vertically within `max_height`. Has
+    /// no effect unless `max_height` is set.
+    pub vertical_align: VerticalAlign,
+    /// The height, in pixels, of the region the text is aligned within. `None`
+    /// leaves the text at the top, as before.
+    pub max_height: Option<f32>,
+    /// When set, `append` first runs a segmentation pass over each appended run:
+    /// it resolves bidi embedding levels and reorders runs into visual order, and
+    /// folds combining marks onto their base character so a grapheme cluster
+    /// shares one advance. Defaults to `false`, the simple left-to-right,
+    /// one-`char`-per-glyph behaviour that leaves LTR ASCII callers unaffected.
+    pub segment: bool,
 }
 
 #[derive(Clone, Copy, Debug)]
@@ -33,6 +51,204 @@ impl Default for HorizontalAlign {
     }
 }
 
+#[derive(Clone, Copy, Debug)]
+pub enum VerticalAlign {
+    Top,
+    Middle,
+    Bottom,
+}
+
+impl Default for VerticalAlign {
+    fn default() -> Self {
+        VerticalAlign::Top
+    }
+}
+
+/// How a line that overflows `max_width` is broken.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WrapStyle {
+    /// Break at the most recent break opportunity (between words), keeping whole
+    /// words intact. Falls back to [`Letter`](WrapStyle::Letter) when a single
+    /// word is wider than the column.
+    Word,
+    /// Break before whichever glyph first overflows, splitting words if need be.
+    Letter,
+}
+
+impl Default for WrapStyle {
+    fn default() -> Self {
+        WrapStyle::Word
+    }
+}
+
+/// A compact subset of the Unicode line-breaking (UAX #14) classes, enough to
+/// decide where a soft break may be inserted.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BreakClass {
+    /// A mandatory break (`\n`, `\u{2028}`).
+    Mandatory,
+    /// Space. A break is allowed after it.
+    Space,
+    /// A break-after character such as a hyphen.
+    Hyphen,
+    /// An ordinary letter or digit. Breaks between two of these are forbidden.
+    Alphabetic,
+}
+
+fn break_class(c: char) -> BreakClass {
+    match c {
+        '\n' | '\u{2028}' => BreakClass::Mandatory,
+        ' ' => BreakClass::Space,
+        '-' | '\u{2010}' => BreakClass::Hyphen,
+        _ => BreakClass::Alphabetic,
+    }
+}
+
+/// Whether a soft break is permitted between a glyph of class `a` and one of
+/// class `b`. A break follows a space or hyphen, but never splits a run of
+/// spaces.
+fn breakable_between(a: BreakClass, b: BreakClass) -> bool {
+    matches!(a, BreakClass::Space | BreakClass::Hyphen) && b != BreakClass::Space
+}
+
+/// A compact subset of the Unicode bidirectional character types, enough to
+/// resolve embedding levels for mixed LTR/RTL text.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum BidiClass {
+    /// A strong left-to-right character.
+    Left,
+    /// A strong right-to-left character (Hebrew, Arabic, …).
+    Right,
+    /// Anything without an inherent direction (punctuation, spaces, symbols).
+    Neutral,
+}
+
+fn bidi_class(c: char) -> BidiClass {
+    match c as u32 {
+        // Hebrew, Arabic, Syriac, and their presentation forms are RTL.
+        0x0590..=0x05FF
+        | 0x0600..=0x06FF
+        | 0x0700..=0x074F
+        | 0x0750..=0x077F
+        | 0xFB1D..=0xFDFF
+        | 0xFE70..=0xFEFF => BidiClass::Right,
+        _ if c.is_alphabetic() || c.is_numeric() => BidiClass::Left,
+        _ => BidiClass::Neutral,
+    }
+}
+
+/// Whether a code point is a combining mark that attaches to the preceding base
+/// character rather than standing as its own grapheme.
+fn is_combining_mark(c: char) -> bool {
+    matches!(
+        c as u32,
+        0x0300..=0x036F
+            | 0x0483..=0x0489
+            | 0x0591..=0x05BD
+            | 0x0610..=0x061A
+            | 0x064B..=0x065F
+            | 0x0670
+            | 0x06D6..=0x06DC
+            | 0x200D
+    )
+}
+
+/// Segment a run for display. Each base character keeps the combining marks
+/// that follow it (they are emitted as zero-advance glyphs stacked on the base
+/// by [`Layout::append`], so a grapheme cluster shares one advance), and the
+/// clusters of each paragraph are reordered by a compact Unicode Bidirectional
+/// Algorithm so right-to-left scripts come out in visual order.
+///
+/// Mandatory break characters (`'\n'`, `'\u{2028}'`) delimit paragraphs and are
+/// never reordered, so a hard break keeps its place in an RTL run.
+fn segment_run(text: &str) -> Vec<char> {
+    let mut out = vec![];
+    let mut paragraph = String::new();
+
+    for c in text.chars() {
+        if c == '\n' || c == '\u{2028}' {
+            out.extend(segment_paragraph(&paragraph));
+            paragraph.clear();
+            out.push(c);
+        } else {
+            paragraph.push(c);
+        }
+    }
+    out.extend(segment_paragraph(&paragraph));
+
+    out
+}
+
+/// Reorder one paragraph's grapheme clusters into visual order. Returns the
+/// base characters in display order, each immediately followed by its combining
+/// marks in their original order.
+fn segment_paragraph(text: &str) -> Vec<char> {
+    // Group codepoints into grapheme clusters: a base character and the marks
+    // that decorate it.
+    let mut clusters: Vec<Vec<char>> = vec![];
+    for c in text.chars() {
+        if is_combining_mark(c) && !clusters.is_empty() {
+            clusters.last_mut().unwrap().push(c);
+        } else {
+            clusters.push(vec![c]);
+        }
+    }
+
+    // The paragraph base direction is that of the first strong character.
+    let base_rtl = clusters
+        .iter()
+        .find_map(|cluster| match bidi_class(cluster[0]) {
+            BidiClass::Left => Some(false),
+            BidiClass::Right => Some(true),
+            BidiClass::Neutral => None,
+        })
+        .unwrap_or(false);
+    let base_level: u8 = if base_rtl { 1 } else { 0 };
+
+    let levels: Vec<u8> = clusters
+        .iter()
+        .map(|cluster| match bidi_class(cluster[0]) {
+            BidiClass::Right => 1,
+            BidiClass::Left if base_rtl => 2,
+            BidiClass::Left => 0,
+            BidiClass::Neutral => base_level,
+        })
+        .collect();
+
+    // Unicode rule L2: from the highest level down to the lowest odd level,
+    // reverse every contiguous run of clusters at or above that level.
+    let mut order: Vec<usize> = (0..clusters.len()).collect();
+    if let Some(&highest) = levels.iter().max() {
+        let lowest_odd = levels
+            .iter()
+            .copied()
+            .filter(|l| l % 2 == 1)
+            .min()
+            .unwrap_or(highest + 1);
+
+        for level in (lowest_odd..=highest).rev() {
+            let mut i = 0;
+            while i < levels.len() {
+                if levels[i] >= level {
+                    let start = i;
+                    while i < levels.len() && levels[i] >= level {
+                        i += 1;
+                    }
+                    order[start..i].reverse();
+                } else {
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    let mut out = vec![];
+    for i in order {
+        out.extend_from_slice(&clusters[i]);
+    }
+    out
+}
+
 /// The gap between lines
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum LineHeight {
@@ -67,6 +283,10 @@ struct Line<U: Clone> {
     // The lowest a glyph descends below the baseline, typically negative
     descent: f32,
 
+    // The glyph index at which the most recent soft-break opportunity within
+    // this line falls, i.e. where a `Word` wrap would start a new line.
+    break_opportunity: Option<usize>,
+
     glyphs: Vec<GlyphPosition<U>>,
 }
 
@@ -84,6 +304,7 @@ impl<U: Clone> Default for Line<U> {
             gap: 0.0,
             ascent: 0.0,
             descent: 0.0,
+            break_opportunity: None,
             glyphs: vec![],
         }
     }
@@ -110,15 +331,20 @@ impl<U: Clone> Layout<U> {
         }
     }
 
-    pub fn append<F: Borrow<Font>>(&mut self, fonts: &[F], styled: StyledText<U>) {
-        let font: &Font = fonts[styled.font_index].borrow();
+    pub fn append<S: GlyphSource, F: Borrow<S>>(&mut self, fonts: &[F], styled: StyledText<U>) {
+        let font: &S = fonts[styled.font_index].borrow();
         let line_metrics = font.horizontal_line_metrics(styled.font_size).unwrap();
 
-        for ch in styled.text.chars() {
-            // Our new method assures us we always have at least one line.
-            let line = self.lines.last_mut().unwrap();
+        // The simple path walks raw chars; the segmentation pass first folds
+        // grapheme clusters and reorders runs for display.
+        let chars: Vec<char> = if self.settings.segment {
+            segment_run(styled.text)
+        } else {
+            styled.text.chars().collect()
+        };
 
-            if ch == '\n' {
+        for ch in chars {
+            if ch == '\n' || ch == '\u{2028}' {
                 self.lines.push(Line::default());
                 continue;
             } else if ch.is_control() {
@@ -126,7 +352,76 @@ impl<U: Clone> Layout<U> {
                 continue;
             }
 
+            // A combining mark stacks on the preceding base without advancing,
+            // so the whole grapheme cluster shares one advance.
+            if self.settings.segment && is_combining_mark(ch) {
+                let line = self.lines.last_mut().unwrap();
+                if let Some(base) = line.glyphs.last() {
+                    let base_x = base.x;
+                    let metrics = font.metrics(ch, styled.font_size);
+                    line.glyphs.push(GlyphPosition {
+                        c: ch,
+                        x: base_x + metrics.xmin as f32,
+                        y: metrics.ymin as f32,
+                        width: metrics.width,
+                        height: metrics.height,
+                        font_index: styled.font_index,
+                        font_size: styled.font_size,
+                        user: styled.user.clone(),
+                    });
+                }
+                continue;
+            }
+
             let metrics = font.metrics(ch, styled.font_size);
+            let class = break_class(ch);
+
+            // Record where a word could break *before* the overflow check, so the
+            // opportunity between a just-placed space and this glyph is visible
+            // when the Word-wrap break decision is made. Otherwise a glyph that is
+            // the first of a new word would wrap at the previous word's start.
+            let record_break = match self.lines.last().unwrap().glyphs.last() {
+                Some(last) => breakable_between(break_class(last.c), class),
+                None => false,
+            };
+            if record_break {
+                let line = self.lines.last_mut().unwrap();
+                line.break_opportunity = Some(line.glyphs.len());
+            }
+
+            // If this glyph would push the current line past max_width, move it
+            // (and, for word wrapping, the rest of the unbroken word) onto a
+            // fresh line before placing it.
+            if let Some(max_width) = self.settings.max_width {
+                let line = self.lines.last().unwrap();
+                if !line.glyphs.is_empty() && line.width + metrics.advance_width > max_width {
+                    match self.settings.wrap_style {
+                        WrapStyle::Letter => self.lines.push(Line::default()),
+                        WrapStyle::Word => match line.break_opportunity {
+                            Some(index) => {
+                                let old = self.lines.last_mut().unwrap();
+                                let carried = old.glyphs.split_off(index);
+                                self.relayout_line::<S, F>(self.lines.len() - 1, fonts);
+
+                                let mut next = Line::default();
+                                next.glyphs = carried;
+                                let at = self.lines.len();
+                                self.lines.push(next);
+                                // Recompute the carried glyphs against the new
+                                // line origin so their `x` no longer includes
+                                // the broken line's width-before-break.
+                                self.relayout_line::<S, F>(at, fonts);
+                            }
+                            // A single word wider than the column: behave like
+                            // letter wrapping and break before this glyph.
+                            None => self.lines.push(Line::default()),
+                        },
+                    }
+                }
+            }
+
+            // Our new method assures us we always have at least one line.
+            let line = self.lines.last_mut().unwrap();
 
             if let LineHeight::Smallest(_) = self.settings.line_height {
                 line.ascent = line.ascent.max(metrics.height as f32 + metrics.ymin as f32);
@@ -173,6 +468,71 @@ impl<U: Clone> Layout<U> {
         }
     }
 
+    /// Recompute a line's width, vertical metrics, and per-glyph `x` from its
+    /// glyphs. Used after a soft break moves glyphs between lines, where the
+    /// carried glyphs must be re-placed relative to the new line origin.
+    fn relayout_line<S: GlyphSource, F: Borrow<S>>(&mut self, index: usize, fonts: &[F]) {
+        let settings = self.settings;
+        let line = &mut self.lines[index];
+
+        line.width = 0.0;
+        line.gap = 0.0;
+        line.ascent = 0.0;
+        line.descent = 0.0;
+        line.break_opportunity = None;
+
+        let glyphs = std::mem::take(&mut line.glyphs);
+        for mut glyph in glyphs {
+            let font: &S = fonts[glyph.font_index].borrow();
+            let line_metrics = font.horizontal_line_metrics(glyph.font_size).unwrap();
+            let metrics = font.metrics(glyph.c, glyph.font_size);
+
+            // A combining mark re-stacks on the preceding base without advancing.
+            if settings.segment && is_combining_mark(glyph.c) {
+                if let Some(base) = line.glyphs.last() {
+                    glyph.x = base.x + metrics.xmin as f32;
+                    glyph.y = metrics.ymin as f32;
+                    line.glyphs.push(glyph);
+                    continue;
+                }
+            }
+
+            if let LineHeight::Smallest(_) = settings.line_height {
+                line.ascent = line.ascent.max(metrics.height as f32 + metrics.ymin as f32);
+                line.descent = line.descent.min(metrics.ymin as f32);
+            } else {
+                line.ascent = line.ascent.max(line_metrics.ascent);
+                line.descent = line.descent.min(line_metrics.descent);
+            }
+
+            line.gap = match settings.line_height {
+                LineHeight::Font => line.gap.max(line_metrics.line_gap),
+                LineHeight::Ratio(ratio) | LineHeight::Smallest(ratio) => {
+                    let min = line.ascent + line.descent;
+                    line.gap.max((min * ratio) - min)
+                }
+            };
+
+            if let Some(last) = line.glyphs.last() {
+                if breakable_between(break_class(last.c), break_class(glyph.c)) {
+                    line.break_opportunity = Some(line.glyphs.len());
+                }
+            }
+
+            let kern = match line.glyphs.last() {
+                Some(last) if last.font_index == glyph.font_index => font
+                    .horizontal_kern(last.c, glyph.c, glyph.font_size)
+                    .unwrap_or(0.0),
+                _ => 0.0,
+            };
+
+            glyph.x = (kern + metrics.xmin as f32 + line.width).max(0.0);
+            glyph.y = metrics.ymin as f32;
+            line.width += metrics.advance_width;
+            line.glyphs.push(glyph);
+        }
+    }
+
     pub fn width(&self) -> f32 {
         let mut width = 0.0;
         for line in &self.lines {
@@ -203,6 +563,21 @@ impl<U: Clone> Layout<U> {
         let mut ret = vec![];
         let settings = self.settings;
         let width = self.width();
+
+        // Shift every glyph down so the block of text sits at the top, middle,
+        // or bottom of the bounded region.
+        let y_align = match settings.max_height {
+            Some(max_height) => {
+                let total_height = self.height();
+                match settings.vertical_align {
+                    VerticalAlign::Top => 0.0,
+                    VerticalAlign::Middle => (max_height - total_height) / 2.0,
+                    VerticalAlign::Bottom => max_height - total_height,
+                }
+            }
+            None => 0.0,
+        };
+
         let mut lastheight = 0.0;
 
         let mut baseline = 0.0;
@@ -225,7 +600,7 @@ impl<U: Clone> Layout<U> {
                 glyph.x += x_offset;
                 // calculate the top-left corner y value of the glyph and then
                 // move it to the baseline
-                glyph.y = glyph.y * -1.0 + baseline - glyph.height as f32;
+                glyph.y = glyph.y * -1.0 + baseline - glyph.height as f32 + y_align;
                 ret.push(glyph);
             }
 
@@ -1,7 +1,15 @@
+mod cache;
+mod image;
 mod layout;
+mod source;
 
+pub use cache::GlyphCache;
 pub use fontdue::Font;
-pub use layout::{GlyphPosition, HorizontalAlign, Layout, LayoutSettings};
+pub use image::{Colors, Image, ImageError};
+pub use layout::{
+    GlyphPosition, HorizontalAlign, Layout, LayoutSettings, VerticalAlign, WrapStyle,
+};
+pub use source::{GlyphSource, PsfError, PsfFont};
 
 use std::{fs::File, io::Read};
 use thiserror::Error;
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+
+use fontdue::{Font, LineMetrics, Metrics, OutlineBounds};
+use thiserror::Error;
+
+/// The glyph operations [`Layout`](crate::Layout) needs from a font, abstracted
+/// so that both outline fonts (`fontdue::Font`) and fixed-cell bitmap fonts
+/// (see [`PsfFont`](crate::PsfFont)) can be laid out through the same pipeline.
+///
+/// The method signatures mirror `fontdue::Font` so the outline implementation
+/// is a straight delegation.
+pub trait GlyphSource {
+    /// The metrics of a single glyph at the given pixel size.
+    fn metrics(&self, character: char, px: f32) -> Metrics;
+    /// The metrics and grey-coverage bitmap of a single glyph.
+    fn rasterize(&self, character: char, px: f32) -> (Metrics, Vec<u8>);
+    /// The ascent, descent, and line gap of a line at the given size.
+    fn horizontal_line_metrics(&self, px: f32) -> Option<LineMetrics>;
+    /// The horizontal kerning adjustment between two glyphs, if any.
+    fn horizontal_kern(&self, left: char, right: char, px: f32) -> Option<f32>;
+}
+
+impl GlyphSource for Font {
+    fn metrics(&self, character: char, px: f32) -> Metrics {
+        Font::metrics(self, character, px)
+    }
+
+    fn rasterize(&self, character: char, px: f32) -> (Metrics, Vec<u8>) {
+        Font::rasterize(self, character, px)
+    }
+
+    fn horizontal_line_metrics(&self, px: f32) -> Option<LineMetrics> {
+        Font::horizontal_line_metrics(self, px)
+    }
+
+    fn horizontal_kern(&self, left: char, right: char, px: f32) -> Option<f32> {
+        Font::horizontal_kern(self, left, right, px)
+    }
+}
+
+/// The four magic bytes a PSFv2 file begins with.
+const PSF2_MAGIC: [u8; 4] = [0x72, 0xb5, 0x4a, 0x86];
+/// Set in the header `flags` when a Unicode table follows the glyph bitmaps.
+const PSF2_HAS_UNICODE_TABLE: u32 = 0x01;
+
+/// A fixed-cell bitmap font parsed from the PC Screen Font (PSFv2) format, the
+/// encoding used by Linux console fonts.
+///
+/// Every glyph is a `width`×`height` cell of 1-bpp coverage, so laying one out
+/// through [`Layout`](crate::Layout) yields crisp, un-antialiased text. Unlike
+/// an outline font the cell size is fixed, so the `px` size passed to the
+/// [`GlyphSource`] methods is ignored.
+pub struct PsfFont {
+    width: usize,
+    height: usize,
+    bytes_per_glyph: usize,
+    // One entry per glyph, each `bytes_per_glyph` long, rows packed MSB-first
+    // and padded to a whole byte.
+    glyphs: Vec<Vec<u8>>,
+    // Maps a code point to its glyph index. `None` when the file carries no
+    // Unicode table, in which case a code point is used as a direct index.
+    unicode: Option<HashMap<char, usize>>,
+}
+
+#[derive(Debug, Error)]
+pub enum PsfError {
+    #[error("data is too short to contain a PSFv2 header")]
+    TooShort,
+    #[error("data is not a PSFv2 font (bad magic)")]
+    BadMagic,
+    #[error("glyph data is truncated: expected {expected} bytes, got {got}")]
+    TruncatedGlyphs { expected: usize, got: usize },
+}
+
+impl PsfFont {
+    /// Parse the bytes of a PSFv2 font.
+    pub fn parse(data: &[u8]) -> Result<Self, PsfError> {
+        if data.len() < 32 {
+            return Err(PsfError::TooShort);
+        }
+        if data[0..4] != PSF2_MAGIC {
+            return Err(PsfError::BadMagic);
+        }
+
+        let u32_at = |offset: usize| {
+            u32::from_le_bytes([
+                data[offset],
+                data[offset + 1],
+                data[offset + 2],
+                data[offset + 3],
+            ])
+        };
+
+        let headersize = u32_at(8) as usize;
+        let flags = u32_at(12);
+        let numglyphs = u32_at(16) as usize;
+        let bytes_per_glyph = u32_at(20) as usize;
+        let height = u32_at(24) as usize;
+        let width = u32_at(28) as usize;
+
+        let glyph_end = headersize + numglyphs * bytes_per_glyph;
+        if data.len() < glyph_end {
+            return Err(PsfError::TruncatedGlyphs {
+                expected: glyph_end,
+                got: data.len(),
+            });
+        }
+
+        let mut glyphs = Vec::with_capacity(numglyphs);
+        for i in 0..numglyphs {
+            let start = headersize + i * bytes_per_glyph;
+            glyphs.push(data[start..start + bytes_per_glyph].to_vec());
+        }
+
+        let unicode = if flags & PSF2_HAS_UNICODE_TABLE != 0 {
+            Some(parse_unicode_table(&data[glyph_end..], numglyphs))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            width,
+            height,
+            bytes_per_glyph,
+            glyphs,
+            unicode,
+        })
+    }
+
+    /// The glyph index a code point maps to. Uses the Unicode table when present
+    /// (glyph 0 for a code point the table does not list), and otherwise the code
+    /// point itself as a direct index; glyph 0 also stands in for any index out
+    /// of range.
+    fn glyph_index(&self, c: char) -> usize {
+        let index = match &self.unicode {
+            Some(table) => table.get(&c).copied().unwrap_or(0),
+            None => c as usize,
+        };
+
+        if index < self.glyphs.len() {
+            index
+        } else {
+            0
+        }
+    }
+
+    /// The metrics shared by every glyph in this fixed-cell font.
+    fn cell_metrics(&self) -> Metrics {
+        Metrics {
+            xmin: 0,
+            ymin: 0,
+            width: self.width,
+            height: self.height,
+            advance_width: self.width as f32,
+            advance_height: 0.0,
+            bounds: OutlineBounds {
+                xmin: 0.0,
+                ymin: 0.0,
+                width: self.width as f32,
+                height: self.height as f32,
+            },
+        }
+    }
+}
+
+impl GlyphSource for PsfFont {
+    fn metrics(&self, _character: char, _px: f32) -> Metrics {
+        self.cell_metrics()
+    }
+
+    fn rasterize(&self, character: char, _px: f32) -> (Metrics, Vec<u8>) {
+        let glyph = &self.glyphs[self.glyph_index(character)];
+        // Each row is padded to a whole number of bytes.
+        let bytes_per_row = self.bytes_per_glyph / self.height.max(1);
+
+        let mut bitmap = Vec::with_capacity(self.width * self.height);
+        for row in 0..self.height {
+            for col in 0..self.width {
+                let byte = glyph[row * bytes_per_row + col / 8];
+                let bit = byte >> (7 - (col % 8)) & 1;
+                bitmap.push(if bit == 1 { 255 } else { 0 });
+            }
+        }
+
+        (self.cell_metrics(), bitmap)
+    }
+
+    fn horizontal_line_metrics(&self, _px: f32) -> Option<LineMetrics> {
+        Some(LineMetrics {
+            ascent: self.height as f32,
+            descent: 0.0,
+            line_gap: 0.0,
+            new_line_size: self.height as f32,
+        })
+    }
+
+    fn horizontal_kern(&self, _left: char, _right: char, _px: f32) -> Option<f32> {
+        None
+    }
+}
+
+/// Walk the optional Unicode table, mapping each code point to the glyph whose
+/// entry it appears in. Each glyph's entry is a run of UTF-8 sequences (with
+/// `0xFE` separating multi-code-point sequences) terminated by `0xFF`.
+fn parse_unicode_table(mut data: &[u8], numglyphs: usize) -> HashMap<char, usize> {
+    let mut map = HashMap::new();
+
+    for index in 0..numglyphs {
+        let end = match data.iter().position(|&b| b == 0xFF) {
+            Some(end) => end,
+            None => break,
+        };
+
+        let entry = &data[..end];
+        for part in entry.split(|&b| b == 0xFE) {
+            if let Ok(text) = std::str::from_utf8(part) {
+                for c in text.chars() {
+                    map.entry(c).or_insert(index);
+                }
+            }
+        }
+
+        data = &data[end + 1..];
+    }
+
+    map
+}